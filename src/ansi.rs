@@ -0,0 +1,216 @@
+//! A small ANSI SGR (`ESC [ ... m`) state machine, so program output that
+//! colors itself (diagnostics, pretty-printed values) renders with real
+//! styling instead of raw escape bytes.
+
+/// The subset of SGR state that actually affects rendering: current
+/// foreground/background color, bold and underline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SgrState {
+    pub fg: Option<&'static str>,
+    pub bg: Option<&'static str>,
+    pub bold: bool,
+    pub underline: bool,
+}
+
+impl SgrState {
+    fn apply(&mut self, code: u32) {
+        match code {
+            0 => *self = SgrState::default(),
+            1 => self.bold = true,
+            4 => self.underline = true,
+            22 => self.bold = false,
+            24 => self.underline = false,
+            30..=37 => self.fg = Some(sgr_color(code - 30, false)),
+            90..=97 => self.fg = Some(sgr_color(code - 90, true)),
+            39 => self.fg = None,
+            40..=47 => self.bg = Some(sgr_color(code - 40, false)),
+            100..=107 => self.bg = Some(sgr_color(code - 100, true)),
+            49 => self.bg = None,
+            _ => (),
+        }
+    }
+
+    /// Render as an inline `style` attribute value.
+    pub fn css(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(fg) = self.fg {
+            parts.push(format!("color:{fg}"));
+        }
+        if let Some(bg) = self.bg {
+            parts.push(format!("background-color:{bg}"));
+        }
+        if self.bold {
+            parts.push("font-weight:bold".to_string());
+        }
+        if self.underline {
+            parts.push("text-decoration:underline".to_string());
+        }
+        parts.join(";")
+    }
+}
+
+fn sgr_color(idx: u32, bright: bool) -> &'static str {
+    const NORMAL: [&str; 8] = [
+        "#000000", "#aa0000", "#00aa00", "#aa5500", "#0000aa", "#aa00aa", "#00aaaa", "#aaaaaa",
+    ];
+    const BRIGHT: [&str; 8] = [
+        "#555555", "#ff5555", "#55ff55", "#ffff55", "#5555ff", "#ff55ff", "#55ffff", "#ffffff",
+    ];
+    (if bright { BRIGHT } else { NORMAL })[idx as usize]
+}
+
+/// A run of output text that shares one `SgrState`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub text: String,
+    pub style: SgrState,
+}
+
+/// Scans raw program output for SGR escape sequences, tracking the current
+/// style across calls to `feed`. A sequence that straddles two `feed`
+/// calls is held back and completed on the next one, rather than being
+/// flushed as garbage text.
+#[derive(Debug, Default)]
+pub struct AnsiParser {
+    state: SgrState,
+    pending: Vec<u8>,
+}
+
+impl AnsiParser {
+    /// Consume `buf`, returning the styled segments it completes. Bytes
+    /// that belong to a not-yet-terminated escape sequence are retained
+    /// internally and prefixed onto the next call's input.
+    pub fn feed(&mut self, buf: &[u8]) -> Vec<Segment> {
+        let mut data = std::mem::take(&mut self.pending);
+        data.extend_from_slice(buf);
+
+        let mut segments = Vec::new();
+        let mut text_start = 0;
+        let mut i = 0;
+
+        while i < data.len() {
+            if data[i] != 0x1b {
+                i += 1;
+                continue;
+            }
+
+            if i + 1 >= data.len() {
+                // Genuinely incomplete: a trailing ESC with nothing after
+                // it yet. Hold it for the next feed.
+                self.flush_text(&data, text_start, i, &mut segments);
+                self.pending = data[i..].to_vec();
+                return segments;
+            }
+
+            if data[i + 1] != b'[' {
+                // Not a CSI sequence at all (cursor save/restore, OSC,
+                // malformed output, ...). There's nothing to wait for, so
+                // drop just the ESC and keep scanning from the next byte
+                // instead of holding it forever.
+                self.flush_text(&data, text_start, i, &mut segments);
+                i += 1;
+                text_start = i;
+                continue;
+            }
+
+            let mut j = i + 2;
+            while j < data.len() && !data[j].is_ascii_alphabetic() {
+                j += 1;
+            }
+            if j >= data.len() {
+                self.flush_text(&data, text_start, i, &mut segments);
+                self.pending = data[i..].to_vec();
+                return segments;
+            }
+
+            self.flush_text(&data, text_start, i, &mut segments);
+            if data[j] == b'm' {
+                let params = String::from_utf8_lossy(&data[i + 2..j]);
+                if params.is_empty() {
+                    self.state = SgrState::default();
+                } else {
+                    for code in params.split(';') {
+                        if let Ok(n) = code.parse::<u32>() {
+                            self.state.apply(n);
+                        }
+                    }
+                }
+            }
+            // Non-SGR CSI sequences (cursor movement, etc.) are swallowed
+            // rather than rendered, since there is nowhere for them to go
+            // in a scrollback pane.
+            i = j + 1;
+            text_start = i;
+        }
+
+        self.flush_text(&data, text_start, data.len(), &mut segments);
+        segments
+    }
+
+    fn flush_text(&self, data: &[u8], start: usize, end: usize, segments: &mut Vec<Segment>) {
+        if start >= end {
+            return;
+        }
+        segments.push(Segment {
+            text: String::from_utf8_lossy(&data[start..end]).into_owned(),
+            style: self.state,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_passes_through_unstyled() {
+        let mut parser = AnsiParser::default();
+        let segs = parser.feed(b"hello");
+        assert_eq!(segs, vec![Segment { text: "hello".into(), style: SgrState::default() }]);
+    }
+
+    #[test]
+    fn sgr_color_code_styles_following_text() {
+        let mut parser = AnsiParser::default();
+        let segs = parser.feed(b"\x1b[31mred\x1b[0m plain");
+        assert_eq!(segs[0].text, "red");
+        assert_eq!(segs[0].style.fg, Some("#aa0000"));
+        assert_eq!(segs[1].text, " plain");
+        assert_eq!(segs[1].style, SgrState::default());
+    }
+
+    #[test]
+    fn bold_and_reset_codes_are_tracked() {
+        let mut parser = AnsiParser::default();
+        let segs = parser.feed(b"\x1b[1mbold\x1b[22mnotbold");
+        assert!(segs[0].style.bold);
+        assert!(!segs[1].style.bold);
+    }
+
+    #[test]
+    fn escape_split_across_feeds_is_held_and_completed() {
+        let mut parser = AnsiParser::default();
+        assert_eq!(parser.feed(b"before\x1b[3"), vec![Segment {
+            text: "before".into(),
+            style: SgrState::default(),
+        }]);
+        let segs = parser.feed(b"1mred");
+        assert_eq!(segs[0].text, "red");
+        assert_eq!(segs[0].style.fg, Some("#aa0000"));
+    }
+
+    #[test]
+    fn non_csi_escape_does_not_stall_output_forever() {
+        let mut parser = AnsiParser::default();
+        assert_eq!(parser.feed(b"\x1bXYZ"), vec![Segment {
+            text: "XYZ".into(),
+            style: SgrState::default(),
+        }]);
+        // A later feed must still produce output; the dropped ESC must not
+        // have been held onto and re-absorbed everything since.
+        assert_eq!(parser.feed(b"hello"), vec![Segment {
+            text: "hello".into(),
+            style: SgrState::default(),
+        }]);
+    }
+}
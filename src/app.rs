@@ -5,6 +5,12 @@ use yew::{prelude::*, html::Scope};
 use spaik::repl::REPL;
 use web_sys::{HtmlElement, MutationObserver};
 
+use crate::ansi::{AnsiParser, Segment};
+use crate::balance;
+use crate::fuzzy;
+use crate::highlight;
+use crate::history::{self, HistoryStore};
+
 const STARTUP_CODE: [&'static str; 1] = [
     r#"(range (n (0 7)) (let ((th (nth (vec "th" "st" "nd" "rd") n "th"))) (println "Hello world, for the {n}{th} time!")))"#,
     // r#"(println "Hello, World!")"#,
@@ -15,7 +21,7 @@ enum HistElem {
     Prompt(String),
     Result(String),
     Error(String),
-    Output(String),
+    Output(Vec<Segment>),
 }
 
 pub struct App {
@@ -24,54 +30,212 @@ pub struct App {
     prompt_ref: NodeRef,
     repl: REPL,
     hist_idx: Option<usize>,
+    history: HistoryStore,
+    search: Option<HistSearch>,
+    completion: Option<Completion>,
 }
 
 pub enum Msg {
     Eval(String),
-    Output(String),
+    Output(Vec<Segment>),
     HistPrev,
     HistNext,
     ScrollBottom,
+    HistSearchStart,
+    HistSearchPush(char),
+    HistSearchBackspace,
+    HistSearchCycle,
+    HistSearchAccept,
+    HistSearchCancel,
+    Complete,
+    CompletionCycle(bool),
+    CompletionAccept,
+    CompletionCancel,
+}
+
+/// State for the Tab-completion candidate list.
+#[derive(Debug)]
+struct Completion {
+    /// The partial symbol under the caret that is being completed.
+    prefix: String,
+    /// Matching global symbols, in the order the REPL reported them.
+    candidates: Vec<String>,
+    cursor: usize,
+    /// Char offset of `prefix`'s first character in the prompt text.
+    start: usize,
+}
+
+/// State for the Ctrl-R reverse incremental history search overlay.
+#[derive(Debug)]
+struct HistSearch {
+    query: String,
+    /// Indices into `hist`, ranked best match first.
+    candidates: Vec<usize>,
+    cursor: usize,
+    /// Prompt text to restore if the search is cancelled.
+    saved_prompt: String,
 }
 
 #[derive(Debug)]
 struct OutWriter {
     link: Scope<App>,
-    buffer: Vec<u8>,
+    ansi: AnsiParser,
+    /// Segments making up the current, not-yet-newline-terminated line.
+    pending_line: Vec<Segment>,
 }
 
 impl OutWriter {
     fn new(link: Scope<App>) -> OutWriter {
         OutWriter {
-            buffer: Vec::new(),
-            link
+            link,
+            ansi: AnsiParser::default(),
+            pending_line: Vec::new(),
+        }
+    }
+
+    fn push_segment(&mut self, seg: Segment) {
+        let mut rest = seg.text.as_str();
+        while let Some(i) = rest.find('\n') {
+            let (line, tail) = rest.split_at(i + 1);
+            if !line.is_empty() {
+                self.pending_line.push(Segment {
+                    text: line.to_string(),
+                    style: seg.style,
+                });
+            }
+            let line = std::mem::take(&mut self.pending_line);
+            self.link.send_message(Msg::Output(line));
+            rest = tail;
+        }
+        if !rest.is_empty() {
+            self.pending_line.push(Segment {
+                text: rest.to_string(),
+                style: seg.style,
+            });
         }
     }
 }
 
 impl io::Write for OutWriter {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.buffer.extend(buf);
-
-        if let Some(i) = self.buffer.iter().rposition(|x| *x == b'\n') {
-            let (first, _last) = self.buffer.split_at(i+1);
-            let s = String::from_utf8_lossy(first);
-            self.link.send_message(Msg::Output(s.into_owned()));
-            self.buffer.drain(..=i).for_each(drop);
+        for seg in self.ansi.feed(buf) {
+            self.push_segment(seg);
         }
-
         Ok(buf.len())
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        let s = String::from_utf8_lossy(&self.buffer);
-        self.link.send_message(Msg::Output(s.into_owned()));
-        self.buffer.clear();
-
+        if !self.pending_line.is_empty() {
+            let line = std::mem::take(&mut self.pending_line);
+            self.link.send_message(Msg::Output(line));
+        }
         Ok(())
     }
 }
 
+/// Insert a line break plus `indent` spaces at the current caret position,
+/// used to auto-indent continuation lines of a multi-line form.
+fn insert_newline_at_caret(indent: usize) {
+    let Some(selection) = window().get_selection().unwrap() else { return };
+    let Ok(range) = selection.get_range_at(0) else { return };
+    range.delete_contents().unwrap();
+
+    let br = document().create_element("br").unwrap();
+    range.insert_node(&br).unwrap();
+    range.set_start_after(&br).unwrap();
+    range.collapse_with_to_start(true).unwrap();
+
+    let text = document().create_text_node(&" ".repeat(indent));
+    range.insert_node(&text).unwrap();
+    range.set_start_after(&text).unwrap();
+    range.collapse_with_to_start(true).unwrap();
+
+    selection.remove_all_ranges().unwrap();
+    selection.add_range(&range).unwrap();
+}
+
+/// Caret position, as a char offset into `elem`'s logical (`inner_text`)
+/// content. `Selection::anchor_offset` alone is only valid within the
+/// anchor's own text node, which is wrong as soon as a multi-line form has
+/// split the prompt into several text nodes around `<br>`s, so this walks
+/// the tree to add up everything before the anchor node.
+fn caret_offset(elem: &HtmlElement) -> usize {
+    let Some(selection) = window().get_selection().unwrap() else { return 0 };
+    let Some(anchor) = selection.anchor_node() else { return 0 };
+    let anchor_offset = selection.anchor_offset() as usize;
+    let elem_node: web_sys::Node = elem.clone().into();
+
+    if anchor.node_type() == web_sys::Node::TEXT_NODE {
+        let mut total = 0usize;
+        count_text_before(&elem_node, &anchor, &mut total);
+        return total + anchor_offset;
+    }
+
+    // The anchor is an element rather than a text leaf - this happens at
+    // element-boundary carets (e.g. right after `insert_newline_at_caret`
+    // or `set_inner_html("<br/>")`). `anchor_offset` is then a child index
+    // into `anchor`, not a char offset, so count whatever precedes `anchor`
+    // itself (unless `anchor` *is* `elem`) plus the text of `anchor`'s
+    // first `anchor_offset` children.
+    let mut total = 0usize;
+    if !anchor.is_same_node(Some(&elem_node)) {
+        count_text_before(&elem_node, &anchor, &mut total);
+    }
+    count_children_text(&anchor, anchor_offset, &mut total);
+    total
+}
+
+/// Add up the logical text length (`<br>` counts as one newline) of every
+/// node before `target` in document order. Returns `true` once `target`
+/// has been found, so the caller stops counting.
+fn count_text_before(node: &web_sys::Node, target: &web_sys::Node, total: &mut usize) -> bool {
+    let children = node.child_nodes();
+    for i in 0..children.length() {
+        let Some(child) = children.item(i) else { continue };
+        if child.is_same_node(Some(target)) {
+            return true;
+        }
+        if child.node_name() == "BR" {
+            *total += 1;
+        } else if child.node_type() == web_sys::Node::TEXT_NODE {
+            *total += child.text_content().unwrap_or_default().chars().count();
+        } else if count_text_before(&child, target, total) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Add up the logical text length of `node`'s first `n` children, for the
+/// case where the caret's anchor offset indexes children rather than chars.
+fn count_children_text(node: &web_sys::Node, n: usize, total: &mut usize) {
+    let children = node.child_nodes();
+    for i in 0..n.min(children.length() as usize) {
+        let Some(child) = children.item(i as u32) else { continue };
+        if child.node_name() == "BR" {
+            *total += 1;
+        } else {
+            *total += child.text_content().unwrap_or_default().chars().count();
+        }
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '(' | ')' | '[' | ']' | '"' | ';')
+}
+
+/// The symbol-ish token ending at `caret` in `text`, and the char offset
+/// where it begins.
+fn token_prefix(text: &str, caret: usize) -> (usize, String) {
+    let chars: Vec<char> = text.chars().collect();
+    let caret = caret.min(chars.len());
+    let mut start = caret;
+    while start > 0 && is_symbol_char(chars[start - 1]) {
+        start -= 1;
+    }
+    (start, chars[start..caret].iter().collect())
+}
+
 fn scroll_bottom() {
     let console = document().get_element_by_id("repl-console").unwrap();
     console.set_scroll_top(console.scroll_height());
@@ -94,12 +258,17 @@ impl Component for App {
     type Properties = ();
 
     fn create(ctx: &Context<Self>) -> Self {
+        let history = HistoryStore::load(history::DEFAULT_CAPACITY);
+        let hist = history.iter().cloned().map(HistElem::Prompt).collect();
         let app = App {
-            hist: Default::default(),
+            hist,
             link: ctx.link().clone(),
             prompt_ref: Default::default(),
             repl: REPL::new(Some(Box::new(OutWriter::new(ctx.link().clone())))),
             hist_idx: None,
+            history,
+            search: None,
+            completion: None,
         };
         for line in STARTUP_CODE {
             ctx.link().send_message(Msg::Eval(line.to_string()));
@@ -119,21 +288,91 @@ impl Component for App {
                 scroll_bottom();
                 return false
             },
+            Msg::HistSearchStart => self.hist_search_start(),
+            Msg::HistSearchPush(c) => self.hist_search_push(c),
+            Msg::HistSearchBackspace => self.hist_search_backspace(),
+            Msg::HistSearchCycle => self.hist_search_cycle(),
+            Msg::HistSearchAccept => self.hist_search_accept(),
+            Msg::HistSearchCancel => self.hist_search_cancel(),
+            Msg::Complete => self.complete(),
+            Msg::CompletionCycle(forward) => self.completion_cycle(forward),
+            Msg::CompletionAccept => self.completion_accept(),
+            Msg::CompletionCancel => self.completion_cancel(),
         }
         true
     }
 
     fn view(&self, _ctx: &Context<Self>) -> Html {
         let link = self.link.clone();
+        let search_active = self.search.is_some();
+        let completion_active = self.completion.is_some();
+        let prompt_ref = self.prompt_ref.clone();
         let onkeydown = move |ev: KeyboardEvent| {
             link.send_message(Msg::ScrollBottom);
+
+            if ev.key() == "r" && ev.ctrl_key() {
+                link.send_message(Msg::HistSearchCycle);
+                ev.prevent_default();
+                return;
+            }
+
+            if search_active {
+                match ev.key().as_str() {
+                    "Enter" => link.send_message(Msg::HistSearchAccept),
+                    "Escape" => link.send_message(Msg::HistSearchCancel),
+                    "Backspace" => link.send_message(Msg::HistSearchBackspace),
+                    key if key.chars().count() == 1 => {
+                        link.send_message(Msg::HistSearchPush(key.chars().next().unwrap()));
+                    }
+                    _ => return,
+                }
+                ev.prevent_default();
+                return;
+            }
+
+            if completion_active {
+                match ev.key().as_str() {
+                    "Tab" => link.send_message(Msg::CompletionCycle(!ev.shift_key())),
+                    "ArrowDown" => link.send_message(Msg::CompletionCycle(true)),
+                    "ArrowUp" => link.send_message(Msg::CompletionCycle(false)),
+                    "Enter" => link.send_message(Msg::CompletionAccept),
+                    "Escape" => link.send_message(Msg::CompletionCancel),
+                    _ => {
+                        // Any other key (typing, navigation, ...) edits the
+                        // prompt out from under the overlay's stale
+                        // prefix/offsets, so drop it rather than risk
+                        // completing against text that has moved on.
+                        link.send_message(Msg::CompletionCancel);
+                        return;
+                    }
+                }
+                ev.prevent_default();
+                return;
+            }
+
             match ev.key().as_str() {
-                "Enter" => {
-                    let elem = document().get_element_by_id("prompt").unwrap();
-                    let text = elem.text_content().unwrap_or_default();
+                "Tab" => link.send_message(Msg::Complete),
+                "Enter" if ev.shift_key() => {
+                    let elem: HtmlElement = prompt_ref.cast().unwrap();
+                    let text = elem.inner_text();
+                    insert_newline_at_caret(balance::net_depth(&text).max(0) as usize * 2);
+                }
+                "Enter" if ev.ctrl_key() => {
+                    let elem: HtmlElement = prompt_ref.cast().unwrap();
+                    let text = elem.inner_text();
                     elem.set_inner_html("<br/>");
                     link.send_message(Msg::Eval(text));
                 }
+                "Enter" => {
+                    let elem: HtmlElement = prompt_ref.cast().unwrap();
+                    let text = elem.inner_text();
+                    if balance::is_complete(&text) {
+                        elem.set_inner_html("<br/>");
+                        link.send_message(Msg::Eval(text));
+                    } else {
+                        insert_newline_at_caret(balance::net_depth(&text).max(0) as usize * 2);
+                    }
+                }
                 "ArrowUp" => link.send_message(Msg::HistPrev),
                 "ArrowDown" => link.send_message(Msg::HistNext),
                 _ => return,
@@ -160,6 +399,8 @@ impl Component for App {
                     {for self.hist.iter().map(|h| self.view_hist(h))}
                 </ul>
                 <div id="prompt-container" class="prompt-container">
+                    { for self.search.as_ref().map(|s| self.view_search(s)) }
+                    { for self.completion.as_ref().map(|c| self.view_completion(c)) }
                     <div id="prompt" class="prompt" ref={&self.prompt_ref} contenteditable="true" {onkeydown} autofocus=true>
                         <br/>
                     </div>
@@ -173,28 +414,88 @@ impl App {
     fn view_hist(&self, h: &HistElem) -> Html {
         match h {
             HistElem::Prompt(s) => html! {
-                <div class="prompt">{s}</div>
+                <div class="prompt">{ for highlight::highlight_spans(s) }</div>
             },
             HistElem::Result(s) => html! {
-                <div class="result">{s}</div>
+                <div class="result">{ for highlight::highlight_spans(s) }</div>
             },
             HistElem::Error(e) => html! {
                 <div class="error"><pre>{e}</pre></div>
             },
-            HistElem::Output(out) => html! {
-                <div class="output"><pre>{out}</pre></div>
+            HistElem::Output(segments) => html! {
+                <div class="output"><pre>
+                    { for segments.iter().map(|seg| html! {
+                        <span style={seg.style.css()}>{&seg.text}</span>
+                    }) }
+                </pre></div>
             }
         }
     }
 
+    fn view_search(&self, search: &HistSearch) -> Html {
+        html! {
+            <div class="hist-search">
+                <div class="hist-search-prompt">
+                    {format!("(reverse-i-search)`{}'", search.query)}
+                </div>
+                <ul class="hist-search-results">
+                    { for search.candidates.iter().take(8).enumerate().map(|(i, &idx)| {
+                        let text = match &self.hist[idx] {
+                            HistElem::Prompt(p) => p.as_str(),
+                            _ => "",
+                        };
+                        let class = if i == search.cursor {
+                            "hist-search-result selected"
+                        } else {
+                            "hist-search-result"
+                        };
+                        html! { <li class={class}>{text}</li> }
+                    }) }
+                </ul>
+            </div>
+        }
+    }
+
+    fn view_completion(&self, completion: &Completion) -> Html {
+        html! {
+            <div class="hist-search">
+                <ul class="hist-search-results">
+                    { for completion.candidates.iter().enumerate().map(|(i, cand)| {
+                        let class = if i == completion.cursor {
+                            "hist-search-result selected"
+                        } else {
+                            "hist-search-result"
+                        };
+                        html! { <li class={class}>{cand}</li> }
+                    }) }
+                </ul>
+            </div>
+        }
+    }
+
     fn eval(&mut self, code: String) {
+        if code.trim() == ":clear-history" {
+            self.history.clear();
+            self.hist.push(HistElem::Prompt(code));
+            self.hist.push(HistElem::Output(vec![Segment {
+                text: "History cleared.".to_string(),
+                style: Default::default(),
+            }]));
+            self.hist_bottom();
+            return;
+        }
+
         let res = self.repl.eval(&code);
-        self.hist.push(HistElem::Prompt(code));
+        self.hist.push(HistElem::Prompt(code.clone()));
+        let ok = res.is_ok();
         match res {
             Ok(Some(res)) => self.hist.push(HistElem::Result(res)),
             Err(e) => self.hist.push(HistElem::Error(e)),
             Ok(None) => ()
         }
+        if ok {
+            self.history.push(code);
+        }
         self.hist_bottom();
     }
 
@@ -238,6 +539,163 @@ impl App {
         self.hist_idx = None
     }
 
+    fn hist_search_start(&mut self) {
+        // Tab-completion and history search are mutually exclusive overlays;
+        // starting this one must close the other.
+        self.completion = None;
+        let elem: HtmlElement = self.prompt_ref.cast().unwrap();
+        let saved_prompt = elem.inner_text();
+        self.search = Some(HistSearch {
+            query: String::new(),
+            candidates: Vec::new(),
+            cursor: 0,
+            saved_prompt,
+        });
+    }
+
+    fn hist_search_matches(&self, query: &str) -> Vec<usize> {
+        let prompts = self.hist.iter().enumerate().filter_map(|(i, h)| match h {
+            HistElem::Prompt(p) => Some((i, p.as_str())),
+            _ => None,
+        });
+        fuzzy::best_matches(query, prompts)
+    }
+
+    fn hist_search_push(&mut self, c: char) {
+        if self.search.is_none() {
+            self.hist_search_start();
+        }
+        let query = {
+            let search = self.search.as_mut().unwrap();
+            search.query.push(c);
+            search.cursor = 0;
+            search.query.clone()
+        };
+        let candidates = self.hist_search_matches(&query);
+        self.search.as_mut().unwrap().candidates = candidates;
+        self.apply_hist_search();
+    }
+
+    fn hist_search_backspace(&mut self) {
+        let Some(search) = &mut self.search else { return };
+        search.query.pop();
+        search.cursor = 0;
+        let query = search.query.clone();
+        let candidates = self.hist_search_matches(&query);
+        self.search.as_mut().unwrap().candidates = candidates;
+        self.apply_hist_search();
+    }
+
+    fn hist_search_cycle(&mut self) {
+        let Some(search) = &mut self.search else {
+            self.hist_search_start();
+            return;
+        };
+        if !search.candidates.is_empty() {
+            search.cursor = (search.cursor + 1) % search.candidates.len();
+        }
+        self.apply_hist_search();
+    }
+
+    fn apply_hist_search(&self) {
+        let Some(search) = &self.search else { return };
+        match search.candidates.get(search.cursor) {
+            Some(&idx) => {
+                if let HistElem::Prompt(p) = &self.hist[idx] {
+                    self.set_prompt_text(p);
+                }
+            }
+            // No candidate and nothing typed yet (e.g. a second Ctrl-R
+            // before any query text): there's nothing to search for, so
+            // leave whatever draft is currently displayed alone instead of
+            // blanking it.
+            None if search.query.is_empty() => (),
+            None => self.set_prompt_text(&search.query),
+        }
+    }
+
+    fn hist_search_accept(&mut self) {
+        self.search = None;
+    }
+
+    fn hist_search_cancel(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.set_prompt_text(&search.saved_prompt);
+        }
+    }
+
+    /// Match the partial token under the caret against the embedded REPL's
+    /// global symbols and either complete it outright (single match) or
+    /// open the candidate overlay (several matches).
+    fn complete(&mut self) {
+        let elem: HtmlElement = self.prompt_ref.cast().unwrap();
+        let text = elem.inner_text();
+        let caret = caret_offset(&elem);
+        let (start, prefix) = token_prefix(&text, caret);
+        if prefix.is_empty() {
+            return;
+        }
+
+        let candidates: Vec<String> = self
+            .repl
+            .symbols()
+            .into_iter()
+            .filter(|s| s.starts_with(&prefix))
+            .collect();
+
+        match candidates.as_slice() {
+            [] => (),
+            [single] => self.replace_token(&text, start, caret, single),
+            _ => {
+                // Tab-completion and history search are mutually exclusive
+                // overlays; opening this one must close the other.
+                self.search = None;
+                self.completion = Some(Completion {
+                    prefix,
+                    candidates,
+                    cursor: 0,
+                    start,
+                });
+            }
+        }
+    }
+
+    fn completion_cycle(&mut self, forward: bool) {
+        let Some(completion) = &mut self.completion else { return };
+        let len = completion.candidates.len();
+        if len == 0 {
+            return;
+        }
+        completion.cursor = if forward {
+            (completion.cursor + 1) % len
+        } else {
+            (completion.cursor + len - 1) % len
+        };
+    }
+
+    fn completion_accept(&mut self) {
+        let Some(completion) = self.completion.take() else { return };
+        let Some(choice) = completion.candidates.get(completion.cursor) else { return };
+        let elem: HtmlElement = self.prompt_ref.cast().unwrap();
+        let text = elem.inner_text();
+        let end = completion.start + completion.prefix.chars().count();
+        self.replace_token(&text, completion.start, end, choice);
+    }
+
+    fn completion_cancel(&mut self) {
+        self.completion = None;
+    }
+
+    /// Replace the `[start, end)` char range of `text` with `replacement`
+    /// and write the result back into the prompt.
+    fn replace_token(&self, text: &str, start: usize, end: usize, replacement: &str) {
+        let chars: Vec<char> = text.chars().collect();
+        let mut new_text: String = chars[..start].iter().collect();
+        new_text.push_str(replacement);
+        new_text.extend(chars[end.min(chars.len())..].iter());
+        self.set_prompt_text(&new_text);
+    }
+
     fn move_caret_end(&self) {
         let range = document().create_range().unwrap();
         let Some(node) = self.prompt_ref.get() else { return };
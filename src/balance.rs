@@ -0,0 +1,112 @@
+//! A small scanner used to decide whether a chunk of SPAIK source is a
+//! complete, balanced expression, so the prompt knows whether `Enter`
+//! should submit or just start a new line.
+
+struct Scan {
+    depth: isize,
+    /// A string literal was opened but never closed before `src` ran out.
+    unterminated_string: bool,
+}
+
+/// Scan `src`, tracking paren/bracket depth while skipping over string
+/// literals (with `\"` escapes) and `;` line comments so delimiters inside
+/// them are not counted.
+fn scan(src: &str) -> Scan {
+    let mut depth: isize = 0;
+    let mut unterminated_string = false;
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                while let Some(&nc) = chars.peek() {
+                    if nc == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+            }
+            '"' => {
+                unterminated_string = true;
+                while let Some(nc) = chars.next() {
+                    match nc {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => {
+                            unterminated_string = false;
+                            break;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            _ => (),
+        }
+    }
+    Scan { depth, unterminated_string }
+}
+
+/// Return the net paren/bracket depth: positive means more delimiters were
+/// opened than closed, zero or negative means every opened delimiter was
+/// closed (or there are stray closing delimiters, which the reader will
+/// reject on its own). String literals are not reflected here: an
+/// unterminated string still yields its delimiter depth; see `is_complete`.
+pub fn net_depth(src: &str) -> isize {
+    scan(src).depth
+}
+
+/// `true` once `src` forms a complete expression and should be submitted.
+/// A string literal left open (e.g. the user just typed an opening `"` and
+/// pressed Enter to keep writing it) is never complete, regardless of
+/// paren depth.
+pub fn is_complete(src: &str) -> bool {
+    let Scan { depth, unterminated_string } = scan(src);
+    !unterminated_string && depth <= 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn balanced_forms_are_complete() {
+        assert!(is_complete("(+ 1 2)"));
+        assert!(is_complete("(let ((x 1)) x)"));
+        assert_eq!(net_depth("(+ 1 2)"), 0);
+    }
+
+    #[test]
+    fn unclosed_forms_are_incomplete() {
+        assert!(!is_complete("(+ 1 2"));
+        assert!(!is_complete("(let ((x 1"));
+        assert_eq!(net_depth("(+ 1 2"), 1);
+    }
+
+    #[test]
+    fn parens_inside_strings_are_not_counted() {
+        assert!(is_complete(r#"(println "(")"#));
+        assert_eq!(net_depth(r#"(println "(")"#), 0);
+    }
+
+    #[test]
+    fn parens_inside_comments_are_not_counted() {
+        assert!(is_complete("(+ 1 2) ; (("));
+        assert_eq!(net_depth("(+ 1 2) ; (("), 0);
+    }
+
+    #[test]
+    fn unterminated_string_is_never_complete() {
+        assert!(!is_complete("\"hello"));
+        assert!(!is_complete("(println \"hello"));
+        // net_depth only counts delimiters, so an open string leaves it at
+        // 0; it is the open string that keeps is_complete false.
+        assert_eq!(net_depth("\"hello"), 0);
+    }
+
+    #[test]
+    fn escaped_quote_does_not_end_the_string() {
+        assert!(is_complete(r#"(println "a\"b")"#));
+    }
+}
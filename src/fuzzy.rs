@@ -0,0 +1,110 @@
+//! A small subsequence/flex matcher used to rank history entries against a
+//! Ctrl-R search query, the same kind of scoring a shell's reverse
+//! incremental search uses.
+
+/// Score `candidate` against `query`, returning `None` if `query` is not a
+/// (possibly non-contiguous) subsequence of `candidate`. Higher scores are
+/// better: consecutive matched characters and matches right after a token
+/// boundary (whitespace or `(`) score higher than scattered ones.
+pub fn score(query: &str, candidate: &str) -> Option<i32> {
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let qchars: Vec<char> = query.chars().collect();
+    let cchars: Vec<char> = candidate.chars().collect();
+    let mut qi = 0;
+    let mut total = 0;
+    let mut prev_matched = false;
+
+    for (ci, &c) in cchars.iter().enumerate() {
+        if qi >= qchars.len() {
+            break;
+        }
+        if c.eq_ignore_ascii_case(&qchars[qi]) {
+            let boundary = ci == 0 || matches!(cchars[ci - 1], ' ' | '\t' | '\n' | '(');
+            total += if prev_matched {
+                5
+            } else if boundary {
+                3
+            } else {
+                1
+            };
+            prev_matched = true;
+            qi += 1;
+        } else {
+            prev_matched = false;
+        }
+    }
+
+    (qi == qchars.len()).then_some(total)
+}
+
+/// Rank `candidates` (an id paired with its text) against `query`, best
+/// match first. Non-matching candidates are dropped.
+pub fn best_matches<'a>(
+    query: &str,
+    candidates: impl Iterator<Item = (usize, &'a str)>,
+) -> Vec<usize> {
+    let mut scored: Vec<(usize, i32)> = candidates
+        .filter_map(|(id, text)| score(query, text).map(|s| (id, s)))
+        .collect();
+    scored.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+    scored.into_iter().map(|(id, _)| id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_query_matches_anything() {
+        assert_eq!(score("", "whatever"), Some(0));
+    }
+
+    #[test]
+    fn non_subsequence_does_not_match() {
+        assert_eq!(score("xyz", "(println \"hi\")"), None);
+    }
+
+    #[test]
+    fn out_of_order_subsequence_does_not_match() {
+        assert_eq!(score("ba", "ab"), None);
+    }
+
+    #[test]
+    fn matching_is_case_insensitive() {
+        assert!(score("PRI", "println").is_some());
+        assert_eq!(score("pri", "println"), score("PRI", "println"));
+    }
+
+    #[test]
+    fn consecutive_matches_score_higher_than_scattered() {
+        let consecutive = score("pri", "println").unwrap();
+        let scattered = score("pfo", "println foo").unwrap();
+        assert!(consecutive > scattered);
+    }
+
+    #[test]
+    fn token_boundary_matches_score_higher() {
+        let at_boundary = score("f", "(foo)").unwrap();
+        let mid_token = score("o", "(foo)").unwrap();
+        assert!(at_boundary > mid_token);
+    }
+
+    #[test]
+    fn best_matches_ranks_best_match_first() {
+        // "pl" is a tight, boundary-anchored match in candidate 1 and a
+        // scattered, non-boundary one in candidate 0.
+        let candidates = [(0, "xpxlx"), (1, "pl")];
+        let ranked = best_matches("pl", candidates.into_iter());
+        assert_eq!(ranked, vec![1, 0]);
+    }
+
+    #[test]
+    fn best_matches_drops_non_matching_candidates() {
+        let candidates = [(0, "foo"), (1, "bar")];
+        let ranked = best_matches("xyz", candidates.into_iter());
+        assert!(ranked.is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+//! A small tokenizing syntax highlighter for SPAIK source, shared between
+//! the echoed prompt history and evaluated results so both render with
+//! consistent coloring.
+//!
+//! Highlighting is driven through a `HighlightHandler` trait, the same
+//! start/text/finish shape as an HTML export handler walking a token
+//! stream: the default `SpanHandler` emits classed `<span>`s, but an
+//! alternate handler (plain text, a different markup dialect, ...) can be
+//! swapped in without touching the tokenizer.
+
+use yew::prelude::*;
+
+const KEYWORDS: &[&str] = &[
+    "let", "if", "fn", "lambda", "define", "defun", "quote", "quasiquote",
+    "unquote", "cond", "when", "unless", "do", "loop", "range", "set!",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Open(char),
+    Close(char),
+    Quote,
+    Str(String),
+    /// A `{...}` interpolation inside a string literal.
+    Interp(String),
+    Number(String),
+    Keyword(String),
+    Symbol(String),
+    Comment(String),
+    Whitespace(String),
+}
+
+/// Split `src` into a flat stream of tokens.
+pub fn tokenize(src: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = src.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '(' | '[' => {
+                chars.next();
+                tokens.push(Token::Open(c));
+            }
+            ')' | ']' => {
+                chars.next();
+                tokens.push(Token::Close(c));
+            }
+            ';' => {
+                let mut s = String::new();
+                while let Some(&nc) = chars.peek() {
+                    if nc == '\n' {
+                        break;
+                    }
+                    s.push(nc);
+                    chars.next();
+                }
+                tokens.push(Token::Comment(s));
+            }
+            '"' => {
+                chars.next();
+                tokens.push(Token::Quote);
+                tokens.extend(tokenize_string_body(&mut chars));
+                tokens.push(Token::Quote);
+            }
+            c if c.is_whitespace() => {
+                let mut s = String::new();
+                while let Some(&nc) = chars.peek() {
+                    if !nc.is_whitespace() {
+                        break;
+                    }
+                    s.push(nc);
+                    chars.next();
+                }
+                tokens.push(Token::Whitespace(s));
+            }
+            c if c.is_ascii_digit() => {
+                let mut s = String::new();
+                while let Some(&nc) = chars.peek() {
+                    if !(nc.is_ascii_digit() || nc == '.') {
+                        break;
+                    }
+                    s.push(nc);
+                    chars.next();
+                }
+                tokens.push(Token::Number(s));
+            }
+            _ => {
+                let mut s = String::new();
+                while let Some(&nc) = chars.peek() {
+                    if nc.is_whitespace() || matches!(nc, '(' | ')' | '[' | ']' | '"' | ';') {
+                        break;
+                    }
+                    s.push(nc);
+                    chars.next();
+                }
+                if s.is_empty() {
+                    // Every char that stops the loop above is handled by an
+                    // earlier arm, so this is a guard: consume one char as
+                    // its own symbol so we always progress.
+                    s.push(chars.next().unwrap());
+                }
+                tokens.push(if KEYWORDS.contains(&s.as_str()) {
+                    Token::Keyword(s)
+                } else {
+                    Token::Symbol(s)
+                });
+            }
+        }
+    }
+
+    tokens
+}
+
+/// Consume the body of a string literal and its closing quote, splitting
+/// out `{...}` interpolations as separate tokens. The returned tokens cover
+/// the body only; the closing quote is not among them.
+fn tokenize_string_body(chars: &mut std::iter::Peekable<std::str::Chars>) -> Vec<Token> {
+    let mut out = Vec::new();
+    let mut buf = String::new();
+
+    loop {
+        match chars.peek() {
+            None | Some('"') => {
+                chars.next();
+                break;
+            }
+            Some('\\') => {
+                buf.push(chars.next().unwrap());
+                if let Some(nc) = chars.next() {
+                    buf.push(nc);
+                }
+            }
+            Some('{') => {
+                if !buf.is_empty() {
+                    out.push(Token::Str(std::mem::take(&mut buf)));
+                }
+                chars.next();
+                let mut interp = String::new();
+                let mut depth = 1;
+                while depth > 0 {
+                    match chars.next() {
+                        None => break,
+                        Some('"') => {
+                            // A nested string literal inside the
+                            // interpolation: consume it whole so a `}`
+                            // within it doesn't look like the closer.
+                            interp.push('"');
+                            loop {
+                                match chars.next() {
+                                    None => break,
+                                    Some('"') => {
+                                        interp.push('"');
+                                        break;
+                                    }
+                                    Some('\\') => {
+                                        interp.push('\\');
+                                        if let Some(nc) = chars.next() {
+                                            interp.push(nc);
+                                        }
+                                    }
+                                    Some(nc) => interp.push(nc),
+                                }
+                            }
+                        }
+                        Some('{') => {
+                            depth += 1;
+                            interp.push('{');
+                        }
+                        Some('}') => {
+                            depth -= 1;
+                            if depth > 0 {
+                                interp.push('}');
+                            }
+                        }
+                        Some(nc) => interp.push(nc),
+                    }
+                }
+                out.push(Token::Interp(interp));
+            }
+            Some(_) => buf.push(chars.next().unwrap()),
+        }
+    }
+
+    if !buf.is_empty() {
+        out.push(Token::Str(buf));
+    }
+    out
+}
+
+/// Handles a token stream the way an HTML export visitor handles an AST
+/// walk: `start`/`finish` bracket the run, `text` is called once per token.
+pub trait HighlightHandler {
+    type Output;
+
+    fn start(&mut self) {}
+    fn text(&mut self, token: &Token);
+    fn finish(self) -> Self::Output;
+}
+
+/// Default handler: renders each token as a classed `<span>`.
+#[derive(Default)]
+pub struct SpanHandler {
+    spans: Vec<Html>,
+}
+
+impl HighlightHandler for SpanHandler {
+    type Output = Vec<Html>;
+
+    fn text(&mut self, token: &Token) {
+        let (class, text) = render_token(token);
+        self.spans.push(match class {
+            Some(class) => html! { <span class={class}>{text}</span> },
+            None => html! { {text} },
+        });
+    }
+
+    fn finish(self) -> Vec<Html> {
+        self.spans
+    }
+}
+
+fn render_token(token: &Token) -> (Option<&'static str>, String) {
+    match token {
+        Token::Open(c) | Token::Close(c) => (Some("tok-paren"), c.to_string()),
+        Token::Quote => (Some("tok-string"), "\"".to_string()),
+        Token::Str(s) => (Some("tok-string"), s.clone()),
+        Token::Interp(s) => (Some("tok-interp"), format!("{{{s}}}")),
+        Token::Number(s) => (Some("tok-number"), s.clone()),
+        Token::Keyword(s) => (Some("tok-keyword"), s.clone()),
+        Token::Symbol(s) => (Some("tok-symbol"), s.clone()),
+        Token::Comment(s) => (Some("tok-comment"), s.clone()),
+        Token::Whitespace(s) => (None, s.clone()),
+    }
+}
+
+/// Run `src` through `handler`, calling `start`/`text`/`finish` in order.
+pub fn highlight<H: HighlightHandler>(src: &str, mut handler: H) -> H::Output {
+    handler.start();
+    for token in tokenize(src) {
+        handler.text(&token);
+    }
+    handler.finish()
+}
+
+/// Convenience wrapper around [`highlight`] using the default `SpanHandler`.
+pub fn highlight_spans(src: &str) -> Vec<Html> {
+    highlight(src, SpanHandler::default())
+}
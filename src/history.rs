@@ -0,0 +1,56 @@
+//! Persistence for REPL prompt history, backed by browser `localStorage`.
+
+use gloo::storage::{LocalStorage, Storage};
+
+const STORAGE_KEY: &str = "webrepl.history";
+
+/// Default cap on the number of entries kept, used by callers that don't
+/// need a different limit.
+pub const DEFAULT_CAPACITY: usize = 500;
+
+/// Holds the prompts typed in previous sessions, mirrored to `localStorage`
+/// on every change so a page reload does not lose them.
+#[derive(Debug)]
+pub struct HistoryStore {
+    entries: Vec<String>,
+    capacity: usize,
+}
+
+impl HistoryStore {
+    /// Load whatever history was saved by a previous session, if any,
+    /// capping it at `capacity` entries.
+    pub fn load(capacity: usize) -> HistoryStore {
+        let mut entries: Vec<String> = LocalStorage::get(STORAGE_KEY).unwrap_or_default();
+        truncate_to(&mut entries, capacity);
+        HistoryStore { entries, capacity }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.entries.iter()
+    }
+
+    /// Record a newly-evaluated prompt and persist the (possibly truncated)
+    /// list back to storage.
+    pub fn push(&mut self, prompt: String) {
+        self.entries.push(prompt);
+        truncate_to(&mut self.entries, self.capacity);
+        self.save();
+    }
+
+    /// Wipe the stored history, e.g. in response to `:clear-history`.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        LocalStorage::delete(STORAGE_KEY);
+    }
+
+    fn save(&self) {
+        let _ = LocalStorage::set(STORAGE_KEY, &self.entries);
+    }
+}
+
+fn truncate_to(entries: &mut Vec<String>, capacity: usize) {
+    if entries.len() > capacity {
+        let overflow = entries.len() - capacity;
+        entries.drain(..overflow);
+    }
+}
@@ -1,4 +1,9 @@
+mod ansi;
 mod app;
+mod balance;
+mod fuzzy;
+mod highlight;
+mod history;
 
 use app::App;
 